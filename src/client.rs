@@ -0,0 +1,609 @@
+use std::sync::atomic::{AtomicI64, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rand::Rng;
+
+use crate::{
+    parse_add_response, AddError, AddSuccess, Notification, Priority, MAX_DESC_LEN, MAX_EVENT_LEN,
+};
+
+const DEFAULT_BASE_URL: &str = "https://prowl.weks.net/publicapi";
+const DEFAULT_MAX_RETRIES: u32 = 3;
+const DEFAULT_BASE_DELAY: Duration = Duration::from_millis(500);
+const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(30);
+const DEFAULT_CONCURRENCY_LIMIT: usize = 10;
+/// Sentinel meaning "no quota observed yet", so a fresh client never
+/// short-circuits before its first real response.
+const UNKNOWN_REMAINING: u32 = u32::MAX;
+
+/// A reusable client for the Prowl API.
+///
+/// Unlike `Notification::add`, which builds a fresh `reqwest::Client` for
+/// every call, `ProwlClient` holds a single pooled HTTP client plus a
+/// configurable base URL and retry policy, so it can be shared across many
+/// notifications.
+#[derive(Debug, Clone)]
+pub struct ProwlClient {
+    http: reqwest::Client,
+    base_url: String,
+    max_retries: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    concurrency_limit: usize,
+    short_circuit_when_exhausted: bool,
+    remaining: Arc<AtomicU32>,
+    reset_date: Arc<AtomicI64>,
+}
+
+impl Default for ProwlClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProwlClient {
+    /// Creates a client pointed at the public Prowl API with the default
+    /// retry policy.
+    pub fn new() -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: DEFAULT_BASE_URL.to_string(),
+            max_retries: DEFAULT_MAX_RETRIES,
+            base_delay: DEFAULT_BASE_DELAY,
+            max_delay: DEFAULT_MAX_DELAY,
+            concurrency_limit: DEFAULT_CONCURRENCY_LIMIT,
+            short_circuit_when_exhausted: true,
+            remaining: Arc::new(AtomicU32::new(UNKNOWN_REMAINING)),
+            reset_date: Arc::new(AtomicI64::new(0)),
+        }
+    }
+
+    /// Points the client at a different Prowl-compatible endpoint, e.g. a
+    /// mock server in tests.
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// Sets the maximum number of retries for transient failures. Defaults
+    /// to 3.
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Sets the maximum number of `add` requests `add_batch` will have
+    /// in-flight at once. Defaults to 10. Clamped to a minimum of 1, since
+    /// 0 would make `add_batch` never send anything.
+    pub fn with_concurrency_limit(mut self, limit: usize) -> Self {
+        self.concurrency_limit = limit.max(1);
+        self
+    }
+
+    /// Controls whether `add`/`add_batch` short-circuit with
+    /// `AddError::RateLimitExhausted` when the last-known quota is 0 and
+    /// its reset time hasn't passed, instead of issuing a doomed request.
+    /// Enabled by default.
+    pub fn with_short_circuit_when_exhausted(mut self, enabled: bool) -> Self {
+        self.short_circuit_when_exhausted = enabled;
+        self
+    }
+
+    /// The number of API calls remaining in the current hour, as of the
+    /// last response this client observed. `u32::MAX` if no response has
+    /// been observed yet.
+    pub fn remaining(&self) -> u32 {
+        self.remaining.load(Ordering::Relaxed)
+    }
+
+    /// The unix timestamp at which `remaining` resets, as of the last
+    /// response this client observed.
+    pub fn reset_date(&self) -> i64 {
+        self.reset_date.load(Ordering::Relaxed)
+    }
+
+    /// Sends a notification, retrying transient failures (connection errors,
+    /// server errors, and rate limiting) with exponential backoff and
+    /// jitter.
+    pub async fn add(&self, notification: &Notification) -> Result<AddSuccess, AddError> {
+        self.check_quota()?;
+
+        let url = notification.add_url(&self.base_url)?;
+
+        let mut attempt = 0;
+        loop {
+            match self.send_once(&url).await {
+                Ok(success) => {
+                    self.record_quota(&success);
+                    return Ok(success);
+                }
+                Err(err) if attempt < self.max_retries && is_transient(&err) => {
+                    tokio::time::sleep(self.delay_for(attempt, &err)).await;
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Returns `AddError::RateLimitExhausted` if this client's last-known
+    /// quota is 0 and we're still before the reset time.
+    fn check_quota(&self) -> Result<(), AddError> {
+        if !self.short_circuit_when_exhausted {
+            return Ok(());
+        }
+
+        let remaining = self.remaining();
+        let reset_date = self.reset_date();
+        if remaining == 0 && now_unix() < reset_date {
+            return Err(AddError::RateLimitExhausted { reset_date });
+        }
+
+        Ok(())
+    }
+
+    fn record_quota(&self, success: &AddSuccess) {
+        self.remaining.store(*success.remaining(), Ordering::Relaxed);
+        self.reset_date.store(*success.reset_date(), Ordering::Relaxed);
+    }
+
+    /// Sends many notifications concurrently, never holding more than
+    /// `concurrency_limit` requests in flight at once, and returns one
+    /// result per input in the same order as `notifications`.
+    pub async fn add_batch(
+        &self,
+        notifications: &[Notification],
+    ) -> Vec<(usize, Result<AddSuccess, AddError>)> {
+        use futures::stream::{self, StreamExt};
+
+        let mut results: Vec<(usize, Result<AddSuccess, AddError>)> =
+            stream::iter(notifications.iter().enumerate())
+                .map(|(index, notification)| async move { (index, self.add(notification).await) })
+                .buffer_unordered(self.concurrency_limit)
+                .collect()
+                .await;
+
+        results.sort_by_key(|(index, _)| *index);
+        results
+    }
+
+    /// Sends `description` as one or more notifications instead of
+    /// rejecting it when it's longer than Prowl's `MAX_DESC_LEN`. The
+    /// description is split on word boundaries into chunks, sent in order
+    /// as `event (1/N)`, `event (2/N)`, etc (just `event` when it fits in one
+    /// notification), all sharing `url`,
+    /// `priority`, and `application`. Sending stops at the first failed
+    /// chunk, so the returned `Vec` may be shorter than the chunk count.
+    pub async fn add_chunked(
+        &self,
+        api_keys: Vec<String>,
+        priority: Option<Priority>,
+        url: Option<String>,
+        application: String,
+        event: String,
+        description: String,
+    ) -> Vec<Result<AddSuccess, AddError>> {
+        let chunks = split_description(&description, MAX_DESC_LEN);
+        let total = chunks.len();
+
+        let mut results = Vec::with_capacity(total);
+        for (index, chunk) in chunks.into_iter().enumerate() {
+            let chunk_event = if total > 1 {
+                let suffix = format!(" ({}/{total})", index + 1);
+                let max_event_len = MAX_EVENT_LEN.saturating_sub(suffix.len());
+                format!("{}{suffix}", truncate_to_len(&event, max_event_len))
+            } else {
+                event.clone()
+            };
+
+            let notification = match Notification::new(
+                api_keys.clone(),
+                priority,
+                url.clone(),
+                application.clone(),
+                chunk_event,
+                chunk,
+            ) {
+                Ok(notification) => notification,
+                Err(err) => {
+                    results.push(Err(AddError::BadRequest(err.to_string())));
+                    break;
+                }
+            };
+
+            let result = self.add(&notification).await;
+            let failed = result.is_err();
+            results.push(result);
+            if failed {
+                break;
+            }
+        }
+
+        results
+    }
+
+    /// Checks whether an API key is valid without sending a notification, by
+    /// calling Prowl's `verify` endpoint.
+    pub async fn verify(&self, api_key: &str) -> Result<(), AddError> {
+        let safe_api_key = urlencoding::encode(api_key);
+        let url = format!("{}/verify?apikey={safe_api_key}", self.base_url);
+        let res = self.http.get(url).send().await?;
+        let body = res.text().await?;
+        parse_add_response(&body)?;
+        Ok(())
+    }
+
+    async fn send_once(&self, url: &str) -> Result<AddSuccess, AddError> {
+        let res = self.http.post(url).send().await?;
+        if res.status().is_server_error() {
+            // A 5xx (possibly from a proxy or timeout gateway in front of
+            // Prowl) may not even be a Prowl XML envelope; don't bother
+            // parsing it, just treat it as transient like the rest of the
+            // 5xx family.
+            return Err(AddError::ServerError);
+        }
+        let body = res.text().await?;
+        parse_add_response(&body)
+    }
+
+    /// How long to wait before the next attempt: for a rate limit with a
+    /// known reset time, wait until then instead of guessing; otherwise (and
+    /// for a rate limit whose reset time the server didn't report) back off
+    /// exponentially with a little jitter.
+    fn delay_for(&self, attempt: u32, err: &AddError) -> Duration {
+        if let AddError::RateLimited { reset_date } = err {
+            if *reset_date > 0 {
+                return wait_until(*reset_date);
+            }
+        }
+
+        let exponential = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt));
+        let capped = exponential.min(self.max_delay);
+        let jitter = Duration::from_millis(rand::thread_rng().gen_range(0..=50));
+        capped + jitter
+    }
+}
+
+fn is_transient(err: &AddError) -> bool {
+    matches!(
+        err,
+        AddError::Send(_) | AddError::ServerError | AddError::RateLimited { .. }
+    )
+}
+
+fn wait_until(reset_date: i64) -> Duration {
+    Duration::from_secs((reset_date - now_unix()).max(0) as u64)
+}
+
+/// Truncates `value` to at most `max_len` bytes, respecting UTF-8 char
+/// boundaries.
+fn truncate_to_len(value: &str, max_len: usize) -> &str {
+    if value.len() <= max_len {
+        return value;
+    }
+
+    let mut end = max_len;
+    while end > 0 && !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    &value[..end]
+}
+
+/// Splits `description` into chunks of at most `max_len` bytes, preferring
+/// to break on whitespace so words aren't split across chunks.
+fn split_description(description: &str, max_len: usize) -> Vec<String> {
+    if description.len() <= max_len {
+        return vec![description.to_string()];
+    }
+
+    let mut chunks = Vec::new();
+    let mut remaining = description;
+
+    while !remaining.is_empty() {
+        if remaining.len() <= max_len {
+            chunks.push(remaining.to_string());
+            break;
+        }
+
+        let mut split_at = max_len;
+        while split_at > 0 && !remaining.is_char_boundary(split_at) {
+            split_at -= 1;
+        }
+        if let Some(word_boundary) = remaining[..split_at].rfind(char::is_whitespace) {
+            if word_boundary > 0 {
+                split_at = word_boundary;
+            }
+        }
+
+        let (chunk, rest) = remaining.split_at(split_at);
+        chunks.push(chunk.trim_end().to_string());
+        remaining = rest.trim_start();
+    }
+
+    chunks
+}
+
+fn now_unix() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, Respond, ResponseTemplate};
+
+    use super::*;
+
+    fn sample_notification(event: &str) -> Notification {
+        Notification::new(
+            vec!["TEST-API-KEY".to_string()],
+            None,
+            None,
+            "Test App".to_string(),
+            event.to_string(),
+            "Test description".to_string(),
+        )
+        .expect("sample notification should satisfy length limits")
+    }
+
+    fn success_body(remaining: u32) -> String {
+        format!(
+            r#"<prowl><success code="200" remaining="{remaining}" resetdate="{}"/></prowl>"#,
+            now_unix() + 3600
+        )
+    }
+
+    struct ConcurrencyTracker {
+        in_flight: Arc<AtomicUsize>,
+        max_in_flight: Arc<AtomicUsize>,
+    }
+
+    impl Respond for ConcurrencyTracker {
+        fn respond(&self, _request: &wiremock::Request) -> ResponseTemplate {
+            let current = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(current, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(50));
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            ResponseTemplate::new(200).set_body_string(success_body(999))
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    async fn add_batch_never_exceeds_the_concurrency_limit() {
+        let server = MockServer::start().await;
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+
+        Mock::given(method("POST"))
+            .and(path("/add"))
+            .respond_with(ConcurrencyTracker {
+                in_flight: in_flight.clone(),
+                max_in_flight: max_in_flight.clone(),
+            })
+            .mount(&server)
+            .await;
+
+        let client = ProwlClient::new()
+            .with_base_url(server.uri())
+            .with_concurrency_limit(3);
+
+        let notifications: Vec<Notification> = (0..9)
+            .map(|i| sample_notification(&format!("event {i}")))
+            .collect();
+
+        let results = client.add_batch(&notifications).await;
+
+        assert_eq!(results.len(), 9);
+        assert!(results.iter().all(|(_, result)| result.is_ok()));
+        assert!(
+            max_in_flight.load(Ordering::SeqCst) <= 3,
+            "saw {} requests in flight at once, expected at most 3",
+            max_in_flight.load(Ordering::SeqCst)
+        );
+    }
+
+    #[tokio::test]
+    async fn add_batch_preserves_input_order_in_its_results() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/add"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(success_body(999)))
+            .mount(&server)
+            .await;
+
+        let client = ProwlClient::new()
+            .with_base_url(server.uri())
+            .with_concurrency_limit(4);
+
+        let notifications: Vec<Notification> = (0..10)
+            .map(|i| sample_notification(&format!("event {i}")))
+            .collect();
+
+        let results = client.add_batch(&notifications).await;
+
+        let indices: Vec<usize> = results.iter().map(|(index, _)| *index).collect();
+        assert_eq!(indices, (0..10).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn add_short_circuits_when_quota_is_exhausted_and_unreset() {
+        // Nothing is listening here; if `add` tried to issue a request
+        // instead of short-circuiting, this would fail with a connection
+        // error rather than `RateLimitExhausted`.
+        let client = ProwlClient {
+            http: reqwest::Client::new(),
+            base_url: "http://127.0.0.1:0".to_string(),
+            max_retries: 0,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(1),
+            concurrency_limit: 1,
+            short_circuit_when_exhausted: true,
+            remaining: Arc::new(AtomicU32::new(0)),
+            reset_date: Arc::new(AtomicI64::new(now_unix() + 3600)),
+        };
+
+        let result = client.add(&sample_notification("exhausted")).await;
+
+        assert!(matches!(result, Err(AddError::RateLimitExhausted { .. })));
+    }
+
+    #[tokio::test]
+    async fn add_ignores_quota_tracking_when_short_circuit_is_disabled() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/add"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(success_body(999)))
+            .mount(&server)
+            .await;
+
+        let client = ProwlClient {
+            http: reqwest::Client::new(),
+            base_url: server.uri(),
+            max_retries: 0,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(1),
+            concurrency_limit: 1,
+            short_circuit_when_exhausted: false,
+            remaining: Arc::new(AtomicU32::new(0)),
+            reset_date: Arc::new(AtomicI64::new(now_unix() + 3600)),
+        };
+
+        let result = client.add(&sample_notification("not exhausted")).await;
+
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn add_records_quota_from_a_successful_response() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/add"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(success_body(42)))
+            .mount(&server)
+            .await;
+
+        let client = ProwlClient::new().with_base_url(server.uri());
+        client
+            .add(&sample_notification("records quota"))
+            .await
+            .expect("mock server always returns success");
+
+        assert_eq!(client.remaining(), 42);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn add_waits_for_the_reported_reset_date_then_retries() {
+        let server = MockServer::start().await;
+        let reset_date = now_unix() + 1;
+
+        Mock::given(method("POST"))
+            .and(path("/add"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(format!(
+                r#"<prowl><error code="406" resetdate="{reset_date}">Rate limit exceeded</error></prowl>"#
+            )))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/add"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(success_body(998)))
+            .mount(&server)
+            .await;
+
+        let client = ProwlClient::new().with_base_url(server.uri());
+        let started = std::time::Instant::now();
+
+        let result = client.add(&sample_notification("rate limited then ok")).await;
+        let elapsed = started.elapsed();
+
+        assert!(result.is_ok());
+        assert!(
+            elapsed >= Duration::from_millis(900),
+            "expected the client to wait for reset_date before retrying, only waited {elapsed:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn verify_maps_success_and_invalid_key() {
+        use wiremock::matchers::query_param;
+
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/verify"))
+            .and(query_param("apikey", "GOOD-KEY"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(success_body(999)))
+            .mount(&server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/verify"))
+            .and(query_param("apikey", "BAD-KEY"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"<prowl><error code="401">Invalid API key</error></prowl>"#,
+            ))
+            .mount(&server)
+            .await;
+
+        let client = ProwlClient::new().with_base_url(server.uri());
+
+        assert!(client.verify("GOOD-KEY").await.is_ok());
+        assert!(matches!(
+            client.verify("BAD-KEY").await,
+            Err(AddError::InvalidApiKey)
+        ));
+    }
+
+    #[test]
+    fn split_description_passes_through_short_descriptions_unchanged() {
+        let description = "short enough";
+        assert_eq!(split_description(description, 10000), vec![description]);
+    }
+
+    #[test]
+    fn split_description_breaks_on_word_boundaries() {
+        let description = "one two three four five";
+        let chunks = split_description(description, 13);
+
+        assert_eq!(chunks, vec!["one two", "three four", "five"]);
+        assert!(chunks.iter().all(|chunk| chunk.len() <= 13));
+    }
+
+    #[test]
+    fn split_description_falls_back_to_a_hard_split_with_no_whitespace_in_budget() {
+        let description = "abcdefghij";
+        let chunks = split_description(description, 4);
+
+        assert_eq!(chunks, vec!["abcd", "efgh", "ij"]);
+    }
+
+    #[test]
+    fn split_description_reassembles_to_the_original_words() {
+        let description = "the quick brown fox jumps over the lazy dog";
+        let chunks = split_description(description, 12);
+
+        let reassembled = chunks.join(" ");
+        assert_eq!(reassembled, description);
+    }
+
+    #[test]
+    fn truncate_to_len_respects_char_boundaries() {
+        let value = "caf\u{e9} au lait";
+        let truncated = truncate_to_len(value, 4);
+
+        assert!(truncated.len() <= 4);
+        assert!(value.starts_with(truncated));
+    }
+}
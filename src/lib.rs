@@ -2,10 +2,13 @@ use derive_getters::Getters;
 use std::fmt::Write;
 use thiserror::Error;
 
+mod client;
+pub use client::ProwlClient;
+
 const MAX_URL_LEN: usize = 512;
 const MAX_APP_LEN: usize = 256;
-const MAX_EVENT_LEN: usize = 1024;
-const MAX_DESC_LEN: usize = 10000;
+pub(crate) const MAX_EVENT_LEN: usize = 1024;
+pub(crate) const MAX_DESC_LEN: usize = 10000;
 
 /// Creates a notification in memory to be sent via prowl.
 #[derive(Debug, Getters)]
@@ -21,7 +24,7 @@ pub struct Notification {
 
 /// The Priority of the notification. Allows prowl clients to
 /// treat the notification differently.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum Priority {
     VeryLow,
     Moderate,
@@ -30,12 +33,48 @@ pub enum Priority {
     Emergency,
 }
 
+/// The details of a successfully delivered notification, as reported by the
+/// Prowl `add` endpoint.
+#[derive(Debug, Getters)]
+pub struct AddSuccess {
+    /// The number of API calls remaining in the current hour.
+    remaining: u32,
+    /// The unix timestamp at which `remaining` resets.
+    reset_date: i64,
+}
+
 /// The error returned by the `add` API on `Notification`.
 #[derive(Debug, Error)]
 pub enum AddError {
-    /// When the response code from the Prowl API is not 200.
-    #[error("The prowl API did not accept the request.")]
-    Api(reqwest::Response),
+    /// Code 400: the request was malformed, e.g. a missing required field.
+    #[error("The prowl API rejected the request as malformed. {0}")]
+    BadRequest(String),
+    /// Code 401: the API key is invalid or unknown.
+    #[error("The prowl API key is invalid.")]
+    InvalidApiKey,
+    /// Code 406: too many requests have been sent this hour.
+    #[error("The prowl API rate limit has been exceeded, resets at {reset_date}.")]
+    RateLimited {
+        /// The unix timestamp at which the rate limit resets.
+        reset_date: i64,
+    },
+    /// Code 409: the API key has not been approved for use yet.
+    #[error("The prowl API key has not been approved.")]
+    NotApproved,
+    /// Code 500: the Prowl API encountered an internal error.
+    #[error("The prowl API encountered an internal error.")]
+    ServerError,
+    /// Returned by `ProwlClient` instead of issuing a request when the
+    /// client's last-known quota is exhausted and the reset time hasn't
+    /// passed yet.
+    #[error("The prowl API quota is exhausted, resets at {reset_date}.")]
+    RateLimitExhausted {
+        /// The unix timestamp at which the quota resets.
+        reset_date: i64,
+    },
+    /// When the response body could not be parsed as the expected XML envelope.
+    #[error("Failed to parse the prowl API response. {0}")]
+    Parse(String),
     /// When reqwest encounters an error sending the request.
     #[error("Failed to send notification to the prowl API. {0}")]
     Send(reqwest::Error),
@@ -150,12 +189,18 @@ impl Notification {
     /// notification.add()?;
     /// ```
     ///
-    pub async fn add(&self) -> Result<(), AddError> {
+    pub async fn add(&self) -> Result<AddSuccess, AddError> {
+        ProwlClient::new().add(self).await
+    }
+
+    /// Builds the `add` endpoint URL, including the query string, for this
+    /// notification against the given API base URL.
+    pub(crate) fn add_url(&self, base_url: &str) -> Result<String, AddError> {
         let safe_application = urlencoding::encode(&self.application);
         let safe_event = urlencoding::encode(&self.event);
         let safe_description = urlencoding::encode(&self.description);
 
-        let mut url: String = "https://prowl.weks.net/publicapi/add".to_string();
+        let mut url: String = format!("{base_url}/add");
         write!(url, "?apikey={}", self.api_keys.join(","))?;
         write!(url, "&application={safe_application}")?;
         write!(url, "&event={safe_event}")?;
@@ -172,14 +217,91 @@ impl Notification {
 
         log::trace!("Built URL {}", url);
 
-        let client = reqwest::Client::new();
-        let res = client.post(url).send().await?;
-        if res.status() != reqwest::StatusCode::OK {
-            log::error!("Failed to add notification, {:?}", res);
-            Err(AddError::Api(res))
-        } else {
-            Ok(())
+        Ok(url)
+    }
+}
+
+/// Parses the XML envelope returned by the Prowl `add`/`verify` endpoints,
+/// e.g. `<prowl><success code="200" remaining="999" resetdate="..."/></prowl>`
+/// or `<prowl><error code="401">Invalid API key</error></prowl>`.
+pub(crate) fn parse_add_response(body: &str) -> Result<AddSuccess, AddError> {
+    use quick_xml::events::Event;
+    use quick_xml::Reader;
+
+    let mut reader = Reader::from_str(body);
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+
+    loop {
+        match reader
+            .read_event_into(&mut buf)
+            .map_err(|e| AddError::Parse(e.to_string()))?
+        {
+            Event::Empty(ref e) | Event::Start(ref e) if e.name().as_ref() == b"success" => {
+                let mut remaining = 0u32;
+                let mut reset_date = 0i64;
+                for attr in e.attributes().flatten() {
+                    let value = String::from_utf8_lossy(&attr.value);
+                    match attr.key.as_ref() {
+                        b"remaining" => remaining = value.parse().unwrap_or_default(),
+                        b"resetdate" => reset_date = value.parse().unwrap_or_default(),
+                        _ => {}
+                    }
+                }
+                return Ok(AddSuccess {
+                    remaining,
+                    reset_date,
+                });
+            }
+            Event::Empty(ref e) if e.name().as_ref() == b"error" => {
+                let (code, reset_date) = error_attrs(e);
+                return Err(add_error_for_code(code, String::new(), reset_date));
+            }
+            Event::Start(ref e) if e.name().as_ref() == b"error" => {
+                let (code, reset_date) = error_attrs(e);
+                let message = reader
+                    .read_text(e.name())
+                    .map(|text| text.into_owned())
+                    .unwrap_or_default();
+                return Err(add_error_for_code(code, message, reset_date));
+            }
+            Event::Eof => {
+                return Err(AddError::Parse(
+                    "reached end of response without a success or error element".to_string(),
+                ))
+            }
+            _ => {}
         }
+        buf.clear();
+    }
+}
+
+/// Reads the `code` and `resetdate` attributes off an `<error>` element.
+fn error_attrs(e: &quick_xml::events::BytesStart<'_>) -> (u16, i64) {
+    let mut code = 0u16;
+    let mut reset_date = 0i64;
+    for attr in e.attributes().flatten() {
+        let value = String::from_utf8_lossy(&attr.value);
+        match attr.key.as_ref() {
+            b"code" => code = value.parse().unwrap_or_default(),
+            b"resetdate" => reset_date = value.parse().unwrap_or_default(),
+            _ => {}
+        }
+    }
+    (code, reset_date)
+}
+
+/// Maps a Prowl API error `code` (see <https://www.prowlapp.com/api.php>) to
+/// a typed `AddError`, falling back to `BadRequest` for unrecognized codes.
+/// `reset_date` is only meaningful for code 406, and is 0 if the server
+/// didn't report one.
+fn add_error_for_code(code: u16, message: String, reset_date: i64) -> AddError {
+    match code {
+        401 => AddError::InvalidApiKey,
+        406 => AddError::RateLimited { reset_date },
+        409 => AddError::NotApproved,
+        500 => AddError::ServerError,
+        _ => AddError::BadRequest(message),
     }
 }
 
@@ -194,3 +316,86 @@ impl From<reqwest::Error> for AddError {
         AddError::Send(error)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_add_response_reads_success() {
+        let body = r#"<prowl><success code="200" remaining="999" resetdate="1700000000"/></prowl>"#;
+        let success = parse_add_response(body).unwrap();
+        assert_eq!(*success.remaining(), 999);
+        assert_eq!(*success.reset_date(), 1700000000);
+    }
+
+    #[test]
+    fn parse_add_response_maps_invalid_api_key() {
+        let body = r#"<prowl><error code="401">Invalid API key</error></prowl>"#;
+        assert!(matches!(
+            parse_add_response(body),
+            Err(AddError::InvalidApiKey)
+        ));
+    }
+
+    #[test]
+    fn parse_add_response_handles_a_self_closed_error_element() {
+        let body = r#"<prowl><error code="401"/></prowl>"#;
+        assert!(matches!(
+            parse_add_response(body),
+            Err(AddError::InvalidApiKey)
+        ));
+    }
+
+    #[test]
+    fn parse_add_response_maps_rate_limited_with_reset_date() {
+        let body = r#"<prowl><error code="406" resetdate="1700000000">Not so fast</error></prowl>"#;
+        assert!(matches!(
+            parse_add_response(body),
+            Err(AddError::RateLimited { reset_date: 1700000000 })
+        ));
+    }
+
+    #[test]
+    fn parse_add_response_maps_not_approved() {
+        let body = r#"<prowl><error code="409">Not approved</error></prowl>"#;
+        assert!(matches!(
+            parse_add_response(body),
+            Err(AddError::NotApproved)
+        ));
+    }
+
+    #[test]
+    fn parse_add_response_maps_server_error() {
+        let body = r#"<prowl><error code="500">Internal error</error></prowl>"#;
+        assert!(matches!(
+            parse_add_response(body),
+            Err(AddError::ServerError)
+        ));
+    }
+
+    #[test]
+    fn parse_add_response_maps_bad_request_with_its_message() {
+        let body = r#"<prowl><error code="400">Missing parameter</error></prowl>"#;
+        match parse_add_response(body) {
+            Err(AddError::BadRequest(message)) => assert_eq!(message, "Missing parameter"),
+            other => panic!("expected BadRequest, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parse_add_response_errors_on_eof_without_an_envelope() {
+        assert!(matches!(
+            parse_add_response("<prowl></prowl>"),
+            Err(AddError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn parse_add_response_errors_on_malformed_xml() {
+        assert!(matches!(
+            parse_add_response("not xml at all"),
+            Err(AddError::Parse(_))
+        ));
+    }
+}